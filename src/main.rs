@@ -1,6 +1,8 @@
 use clap::{Parser, Subcommand, ValueEnum};
+use futures::future::join_all;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::BTreeMap;
 use std::io::Write;
 
 #[derive(Clone, ValueEnum, Debug)]
@@ -17,6 +19,10 @@ struct Args {
     #[arg(value_enum, long, short, default_value_t = PrintFormat::Normal)]
     format: PrintFormat,
 
+    /// Bearer token for the management API, also read from WIMPOD_TOKEN
+    #[arg(long, env = "WIMPOD_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -24,7 +30,8 @@ struct Args {
 #[derive(Subcommand, Debug)]
 enum Commands {
     Stats {
-        namespace: String,
+        #[arg(required = true, num_args = 1..)]
+        namespaces: Vec<String>,
         #[arg(long, short)]
         include_top_queries: bool,
     },
@@ -41,6 +48,57 @@ enum Commands {
         from: String,
         to: String,
     },
+
+    Config {
+        namespace: String,
+
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+
+    Watch {
+        namespace: String,
+        interval_secs: u64,
+    },
+
+    Diagnostics,
+
+    /// Reads newline-delimited JSON requests from stdin and writes one
+    /// newline-delimited JSON response per request to stdout.
+    Batch,
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigAction {
+    Get,
+
+    Set {
+        /// Leave unset to keep the current value, pass on its own to
+        /// block, or give an explicit true/false to set either way
+        /// (e.g. `--block-reads=false` to unblock).
+        #[arg(
+            long,
+            num_args = 0..=1,
+            default_missing_value = "true",
+            value_parser = clap::value_parser!(bool)
+        )]
+        block_reads: Option<bool>,
+
+        /// Same tri-state behavior as `--block-reads`.
+        #[arg(
+            long,
+            num_args = 0..=1,
+            default_missing_value = "true",
+            value_parser = clap::value_parser!(bool)
+        )]
+        block_writes: Option<bool>,
+
+        #[arg(long)]
+        block_reason: Option<String>,
+
+        #[arg(long)]
+        max_db_size: Option<String>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -68,136 +126,279 @@ struct Config {
     max_db_size: Option<String>,
 }
 
+/// The shape of `/v1/diagnostics` can vary by server version, so only the
+/// fields we know about are typed; everything else is kept under `extra`
+/// so new server fields still surface in JSON output.
+#[derive(Serialize, Deserialize, Debug)]
+struct Diagnostics {
+    connections: u64,
+    in_flight_queries: u64,
+    replication_lag_ms: u64,
+    #[serde(flatten)]
+    extra: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum BatchOp {
+    CreateNamespace { name: String },
+    DeleteNamespace { name: String },
+    Fork { from: String, to: String },
+    Stats { namespace: String },
+    GetConfig { namespace: String },
+    SetConfig {
+        namespace: String,
+        block_reads: Option<bool>,
+        block_writes: Option<bool>,
+        block_reason: Option<String>,
+        max_db_size: Option<String>,
+    },
+    Diagnostics,
+}
+
+/// JSON-RPC-style error codes so callers can tell failure modes apart
+/// instead of matching on message strings.
+mod error_code {
+    pub const INVALID_PARAMS: i32 = -32602;
+    pub const NOT_FOUND: i32 = -32001;
+    pub const CONFLICT: i32 = -32002;
+    pub const INTERNAL_ERROR: i32 = -32603;
+    pub const TRANSPORT_ERROR: i32 = -32000;
+    pub const AUTH: i32 = -32003;
+}
+
 struct Server {
     base_url: String,
-    client: reqwest::blocking::Client,
+    token: Option<String>,
+    client: reqwest::Client,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 struct ServerError {
-    error: String,
+    code: i32,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<serde_json::Value>,
+}
+
+impl ServerError {
+    fn transport(err: reqwest::Error) -> Self {
+        Self {
+            code: error_code::TRANSPORT_ERROR,
+            message: err.to_string(),
+            data: None,
+        }
+    }
+
+    /// Builds a `ServerError` from a non-2xx response, mapping the HTTP
+    /// status to our error taxonomy and falling back to parsing the
+    /// server's `{ error: String }` body into `message`.
+    async fn from_response(res: reqwest::Response) -> Self {
+        let code = match res.status().as_u16() {
+            401 | 403 => error_code::AUTH,
+            404 => error_code::NOT_FOUND,
+            409 => error_code::CONFLICT,
+            500..=599 => error_code::INTERNAL_ERROR,
+            _ => error_code::INVALID_PARAMS,
+        };
+
+        #[derive(Deserialize)]
+        struct RawError {
+            error: String,
+        }
+
+        let message = res
+            .json::<RawError>()
+            .await
+            .map(|e| e.error)
+            .unwrap_or_else(|_| "Request failed".to_string());
+
+        Self {
+            code,
+            message,
+            data: None,
+        }
+    }
 }
 
 impl Server {
-    fn new(base_url: String) -> Self {
+    fn new(base_url: String, token: Option<String>) -> Self {
         Self {
             base_url,
-            client: reqwest::blocking::Client::new(),
+            token,
+            client: reqwest::Client::new(),
         }
     }
 
-    fn create_namespace(&self, namespace: &str) -> Result<(), ServerError> {
+    /// Attaches the bearer token, if any, to an outgoing request.
+    fn authed(&self, builder: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.token {
+            Some(token) => builder.bearer_auth(token),
+            None => builder,
+        }
+    }
+
+    async fn create_namespace(
+        &self,
+        namespace: &str,
+    ) -> Result<(), ServerError> {
         let url =
             format!("{}/v1/namespaces/{}/create", self.base_url, namespace);
-        let res = self.client.post(url).json(&json!({})).send().unwrap();
+        let res = self
+            .authed(self.client.post(url).json(&json!({})))
+            .send()
+            .await
+            .map_err(ServerError::transport)?;
 
         if !res.status().is_success() {
-            let error = res.json::<ServerError>().unwrap();
-            return Err(error);
+            return Err(ServerError::from_response(res).await);
         }
 
         Ok(())
     }
 
-    fn delete_namespace(&self, namespace: &str) -> Result<(), ServerError> {
+    async fn delete_namespace(
+        &self,
+        namespace: &str,
+    ) -> Result<(), ServerError> {
         let url = format!("{}/v1/namespaces/{}", self.base_url, namespace);
-        let res = self.client.delete(url).send().unwrap();
+        let res = self
+            .authed(self.client.delete(url))
+            .send()
+            .await
+            .map_err(ServerError::transport)?;
 
         if !res.status().is_success() {
-            let error = res.json::<ServerError>().unwrap();
-            return Err(error);
+            return Err(ServerError::from_response(res).await);
         }
 
         Ok(())
     }
 
-    fn fork_namespace(&self, from: &str, to: &str) -> Result<(), ServerError> {
+    async fn fork_namespace(
+        &self,
+        from: &str,
+        to: &str,
+    ) -> Result<(), ServerError> {
         let url =
             format!("{}/v1/namespaces/{}/fork/{}", self.base_url, from, to);
-        let res = self.client.post(url).send().unwrap();
+        let res = self
+            .authed(self.client.post(url))
+            .send()
+            .await
+            .map_err(ServerError::transport)?;
 
         if !res.status().is_success() {
-            let error = res.json::<ServerError>().unwrap();
-            return Err(error);
+            return Err(ServerError::from_response(res).await);
         }
 
         Ok(())
     }
 
-    fn namespace_stats(&self, namespace: &str) -> Option<NamespaceStats> {
+    async fn namespace_stats(
+        &self,
+        namespace: &str,
+    ) -> Result<NamespaceStats, ServerError> {
         let url =
             format!("{}/v1/namespaces/{}/stats", self.base_url, namespace);
-        let res = self.client.get(url).send().ok()?;
+        let res = self
+            .authed(self.client.get(url))
+            .send()
+            .await
+            .map_err(ServerError::transport)?;
 
         if !res.status().is_success() {
-            println!("Res: {:#?}", res.json::<serde_json::Value>());
-            return None;
+            return Err(ServerError::from_response(res).await);
         }
 
-        let res = res.json::<NamespaceStats>().ok()?;
-
-        Some(res)
+        res.json::<NamespaceStats>()
+            .await
+            .map_err(ServerError::transport)
     }
 
-    fn get_namespace_config(&self, namespace: &str) -> Option<Config> {
+    async fn get_namespace_config(
+        &self,
+        namespace: &str,
+    ) -> Result<Config, ServerError> {
         let url =
             format!("{}/v1/namespaces/{}/config", self.base_url, namespace);
-        let res = self.client.get(url).send().ok()?;
+        let res = self
+            .authed(self.client.get(url))
+            .send()
+            .await
+            .map_err(ServerError::transport)?;
 
         if !res.status().is_success() {
-            println!("Res: {:#?}", res.json::<serde_json::Value>());
-            return None;
+            return Err(ServerError::from_response(res).await);
         }
 
-        // println!("Res: {:#?}", res.json::<serde_json::Value>());
-        let res = res.json::<Config>().ok()?;
-
-        Some(res)
+        res.json::<Config>().await.map_err(ServerError::transport)
     }
 
-    fn set_namespace_config(
+    async fn set_namespace_config(
         &self,
         namespace: &str,
         config: &Config,
-    ) -> Option<()> {
+    ) -> Result<(), ServerError> {
         let url =
             format!("{}/v1/namespaces/{}/config", self.base_url, namespace);
-        let res = self.client.post(url).json(config).send().ok()?;
+        let res = self
+            .authed(self.client.post(url).json(config))
+            .send()
+            .await
+            .map_err(ServerError::transport)?;
 
         if !res.status().is_success() {
-            println!("Res: {:#?}", res.json::<serde_json::Value>());
-            return None;
+            return Err(ServerError::from_response(res).await);
         }
 
-        None
+        Ok(())
     }
 
-    // .route(
-    //     "/v1/namespaces/:namespace/config",
-    //     get(handle_get_config).post(handle_post_config),
-    // )
-    //
-    // .route("/v1/diagnostics", get(handle_diagnostics))
+    async fn diagnostics(&self) -> Result<Diagnostics, ServerError> {
+        let url = format!("{}/v1/diagnostics", self.base_url);
+        let res = self
+            .authed(self.client.get(url))
+            .send()
+            .await
+            .map_err(ServerError::transport)?;
+
+        if !res.status().is_success() {
+            return Err(ServerError::from_response(res).await);
+        }
+
+        res.json::<Diagnostics>()
+            .await
+            .map_err(ServerError::transport)
+    }
 }
 
-fn print_stats(stats: &NamespaceStats, format: PrintFormat) {
+fn print_stats(stats: &BTreeMap<String, NamespaceStats>, format: PrintFormat) {
     match format {
         PrintFormat::Normal => {
-            println!("Rows Read: {}", stats.rows_read_count);
-            println!("Rows Written: {}", stats.rows_written_count);
-            println!("Storage Used (B): {}", stats.storage_bytes_used);
-            println!(
-                "Write Requests Delegated: {}",
-                stats.write_requests_delegated
-            );
-            println!("Replication Index: {}", stats.replication_index);
-            if !stats.top_queries.is_empty() {
-                println!("Top Queries (RR = Rows Read : RW = Rows Written):");
-                for (i, query) in stats.top_queries.iter().enumerate() {
+            for (namespace, stats) in stats {
+                println!("== {} ==", namespace);
+                println!("Rows Read: {}", stats.rows_read_count);
+                println!("Rows Written: {}", stats.rows_written_count);
+                println!("Storage Used (B): {}", stats.storage_bytes_used);
+                println!(
+                    "Write Requests Delegated: {}",
+                    stats.write_requests_delegated
+                );
+                println!("Replication Index: {}", stats.replication_index);
+                if !stats.top_queries.is_empty() {
                     println!(
-                        "{}: RR: {} RW: {} Query: {}",
-                        i, query.rows_read, query.rows_written, query.query
+                        "Top Queries (RR = Rows Read : RW = Rows Written):"
                     );
+                    for (i, query) in stats.top_queries.iter().enumerate() {
+                        println!(
+                            "{}: RR: {} RW: {} Query: {}",
+                            i,
+                            query.rows_read,
+                            query.rows_written,
+                            query.query
+                        );
+                    }
                 }
             }
         }
@@ -212,6 +413,85 @@ fn print_stats(stats: &NamespaceStats, format: PrintFormat) {
     }
 }
 
+fn print_config(config: &Config, format: PrintFormat) {
+    match format {
+        PrintFormat::Normal => {
+            println!("Block Reads: {}", config.block_reads);
+            println!("Block Writes: {}", config.block_writes);
+            if let Some(reason) = &config.block_reason {
+                println!("Block Reason: {}", reason);
+            }
+            if let Some(size) = &config.max_db_size {
+                println!("Max DB Size: {}", size);
+            }
+        }
+
+        PrintFormat::Json => {
+            let j = serde_json::to_string_pretty(
+                &json!({ "success": true, "config": &config }),
+            )
+            .expect("Failed to convert config to json");
+            write_str(&j);
+        }
+    }
+}
+
+/// A partial update to a namespace `Config`: `None` means "leave
+/// untouched", `Some(_)` means "set to this value". Shared between the
+/// `config set` CLI action and the `SetConfig` batch op so the two wire
+/// protocols merge flags identically.
+struct ConfigPatch {
+    block_reads: Option<bool>,
+    block_writes: Option<bool>,
+    block_reason: Option<String>,
+    max_db_size: Option<String>,
+}
+
+fn apply_config_patch(config: &mut Config, patch: ConfigPatch) {
+    if let Some(block_reads) = patch.block_reads {
+        config.block_reads = block_reads;
+    }
+
+    if let Some(block_writes) = patch.block_writes {
+        config.block_writes = block_writes;
+    }
+
+    if patch.block_reason.is_some() {
+        config.block_reason = patch.block_reason;
+    }
+
+    if patch.max_db_size.is_some() {
+        config.max_db_size = patch.max_db_size;
+    }
+}
+
+fn print_diagnostics(diagnostics: &Diagnostics, format: PrintFormat) {
+    match format {
+        PrintFormat::Normal => {
+            println!("Connections: {}", diagnostics.connections);
+            println!(
+                "In-Flight Queries: {}",
+                diagnostics.in_flight_queries
+            );
+            println!(
+                "Replication Lag (ms): {}",
+                diagnostics.replication_lag_ms
+            );
+            for (key, value) in &diagnostics.extra {
+                println!("{}: {}", key, value);
+            }
+        }
+
+        PrintFormat::Json => {
+            let j = serde_json::to_string_pretty(
+                &json!({ "success": true, "diagnostics": &diagnostics }),
+            )
+            .expect("Failed to convert diagnostics to json");
+            write_str(&j);
+        }
+    }
+}
+
 fn write_str(s: &str) {
     let stdout = std::io::stdout();
     let mut lock = stdout.lock();
@@ -219,15 +499,33 @@ fn write_str(s: &str) {
     // NOTE(patrik): Just exit when an error occurs because
     // I got a problem with broken pipes when piping to an
     // program that doesn't exist
-    if let Err(_) = writeln!(lock, "{}", s) {
+    if writeln!(lock, "{}", s).is_err() {
         std::process::exit(0);
     }
+
+    if lock.flush().is_err() {
+        std::process::exit(0);
+    }
+}
+
+/// Maps an error code to a process exit status so scripts can branch on
+/// `$?` instead of scraping stderr.
+fn exit_code_for(code: i32) -> i32 {
+    match code {
+        error_code::NOT_FOUND => 2,
+        error_code::CONFLICT => 3,
+        error_code::INVALID_PARAMS => 4,
+        error_code::TRANSPORT_ERROR => 5,
+        error_code::INTERNAL_ERROR => 6,
+        error_code::AUTH => 7,
+        _ => 1,
+    }
 }
 
-fn print_server_error(err: ServerError, format: PrintFormat) {
+fn print_server_error(err: ServerError, format: PrintFormat) -> ! {
     match format {
         PrintFormat::Normal => {
-            eprintln!("Error: {}", err.error);
+            eprintln!("Error [{}]: {}", err.code, err.message);
         }
 
         PrintFormat::Json => {
@@ -238,7 +536,7 @@ fn print_server_error(err: ServerError, format: PrintFormat) {
         }
     }
 
-    std::process::exit(-1);
+    std::process::exit(exit_code_for(err.code));
 }
 
 fn print_success(format: PrintFormat) {
@@ -254,63 +552,303 @@ fn print_success(format: PrintFormat) {
     }
 }
 
-fn main() {
+/// Dispatches a single batch request against `Server` and returns the
+/// `ok` payload for the response line.
+async fn run_batch_op(
+    server: &Server,
+    op: BatchOp,
+) -> Result<serde_json::Value, ServerError> {
+    match op {
+        BatchOp::CreateNamespace { name } => {
+            server.create_namespace(&name).await?;
+            Ok(json!({}))
+        }
+
+        BatchOp::DeleteNamespace { name } => {
+            server.delete_namespace(&name).await?;
+            Ok(json!({}))
+        }
+
+        BatchOp::Fork { from, to } => {
+            server.fork_namespace(&from, &to).await?;
+            Ok(json!({}))
+        }
+
+        BatchOp::Stats { namespace } => {
+            let stats = server.namespace_stats(&namespace).await?;
+            Ok(serde_json::to_value(stats)
+                .expect("Failed to convert stats to json"))
+        }
+
+        BatchOp::GetConfig { namespace } => {
+            let config = server.get_namespace_config(&namespace).await?;
+            Ok(serde_json::to_value(config)
+                .expect("Failed to convert config to json"))
+        }
+
+        BatchOp::SetConfig {
+            namespace,
+            block_reads,
+            block_writes,
+            block_reason,
+            max_db_size,
+        } => {
+            let mut config = server.get_namespace_config(&namespace).await?;
+
+            apply_config_patch(
+                &mut config,
+                ConfigPatch {
+                    block_reads,
+                    block_writes,
+                    block_reason,
+                    max_db_size,
+                },
+            );
+
+            server.set_namespace_config(&namespace, &config).await?;
+
+            Ok(serde_json::to_value(config)
+                .expect("Failed to convert config to json"))
+        }
+
+        BatchOp::Diagnostics => {
+            let diagnostics = server.diagnostics().await?;
+            Ok(serde_json::to_value(diagnostics)
+                .expect("Failed to convert diagnostics to json"))
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
     let args = Args::parse();
 
-    let server = Server::new(args.base_url);
+    let server = Server::new(args.base_url, args.token);
 
     match args.command {
         Commands::Stats {
-            namespace,
+            namespaces,
             include_top_queries,
         } => {
-            let mut stats = server
-                .namespace_stats(&namespace)
-                .expect("Failed to retrive namespace stats");
+            let results = join_all(namespaces.iter().map(|namespace| async {
+                (namespace.clone(), server.namespace_stats(namespace).await)
+            }))
+            .await;
+
+            let mut stats = BTreeMap::new();
+            for (namespace, result) in results {
+                let mut namespace_stats = match result {
+                    Ok(stats) => stats,
+                    Err(e) => print_server_error(e, args.format),
+                };
+
+                if !include_top_queries {
+                    namespace_stats.top_queries.clear();
+                }
 
-            if !include_top_queries {
-                stats.top_queries.clear();
+                stats.insert(namespace, namespace_stats);
             }
 
             print_stats(&stats, args.format);
         }
 
         Commands::CreateNamespace { name } => {
-            match server.create_namespace(&name) {
+            match server.create_namespace(&name).await {
                 Ok(_) => print_success(args.format),
                 Err(e) => print_server_error(e, args.format),
             }
         }
 
         Commands::DeleteNamespace { name } => {
-            match server.delete_namespace(&name) {
+            match server.delete_namespace(&name).await {
                 Ok(_) => print_success(args.format),
                 Err(e) => print_server_error(e, args.format),
             }
         }
 
         Commands::Fork { from, to } => {
-            match server.fork_namespace(&from, &to) {
+            match server.fork_namespace(&from, &to).await {
                 Ok(_) => print_success(args.format),
                 Err(e) => print_server_error(e, args.format),
             }
         }
-    }
 
-    // let stats = server.namespace_stats("db1");
-    // println!("{:#?}", stats);
-    //
-    // let mut config = server.get_namespace_config("db1").unwrap();
-    // println!("Config: {:#?}", config);
-    //
-    // config.max_db_size = Some("500.0 PB".to_string());
-    //
-    // server.set_namespace_config("db1", &config);
-    //
-    // let config = server.get_namespace_config("db1").unwrap();
-    // println!("Config: {:#?}", config);
-
-    // server.fork_namespace("db1", "db3");
-    // server.delete_namespace("db3");
-    // server.create_namespace("db3");
+        Commands::Config { namespace, action } => match action {
+            ConfigAction::Get => {
+                let config = match server.get_namespace_config(&namespace).await
+                {
+                    Ok(config) => config,
+                    Err(e) => print_server_error(e, args.format),
+                };
+
+                print_config(&config, args.format);
+            }
+
+            ConfigAction::Set {
+                block_reads,
+                block_writes,
+                block_reason,
+                max_db_size,
+            } => {
+                let mut config =
+                    match server.get_namespace_config(&namespace).await {
+                        Ok(config) => config,
+                        Err(e) => print_server_error(e, args.format),
+                    };
+
+                apply_config_patch(
+                    &mut config,
+                    ConfigPatch {
+                        block_reads,
+                        block_writes,
+                        block_reason,
+                        max_db_size,
+                    },
+                );
+
+                if let Err(e) =
+                    server.set_namespace_config(&namespace, &config).await
+                {
+                    print_server_error(e, args.format);
+                }
+
+                let config = match server.get_namespace_config(&namespace).await
+                {
+                    Ok(config) => config,
+                    Err(e) => print_server_error(e, args.format),
+                };
+
+                print_config(&config, args.format);
+            }
+        },
+
+        Commands::Watch {
+            namespace,
+            interval_secs,
+        } => {
+            if interval_secs == 0 {
+                print_server_error(
+                    ServerError {
+                        code: error_code::INVALID_PARAMS,
+                        message: "interval_secs must be greater than 0"
+                            .to_string(),
+                        data: None,
+                    },
+                    args.format,
+                );
+            }
+
+            let mut previous: Option<NamespaceStats> = None;
+            let mut interval = tokio::time::interval(
+                std::time::Duration::from_secs(interval_secs),
+            );
+
+            loop {
+                interval.tick().await;
+
+                let sample = match server.namespace_stats(&namespace).await {
+                    Ok(stats) => {
+                        let deltas = match &previous {
+                            Some(prev) => json!({
+                                "rows_read_delta": stats.rows_read_count as i64 - prev.rows_read_count as i64,
+                                "rows_written_delta": stats.rows_written_count as i64 - prev.rows_written_count as i64,
+                                "storage_bytes_delta": stats.storage_bytes_used as i64 - prev.storage_bytes_used as i64,
+                            }),
+                            None => json!({
+                                "rows_read_delta": null,
+                                "rows_written_delta": null,
+                                "storage_bytes_delta": null,
+                            }),
+                        };
+
+                        let sample = json!({
+                            "namespace": namespace,
+                            "stats": &stats,
+                            "deltas": deltas,
+                        });
+
+                        previous = Some(stats);
+                        sample
+                    }
+
+                    Err(e) => json!({ "namespace": namespace, "error": e }),
+                };
+
+                let j = serde_json::to_string(&sample)
+                    .expect("Failed to convert watch sample to json");
+                write_str(&j);
+            }
+        }
+
+        Commands::Diagnostics => {
+            let diagnostics = match server.diagnostics().await {
+                Ok(diagnostics) => diagnostics,
+                Err(e) => print_server_error(e, args.format),
+            };
+
+            print_diagnostics(&diagnostics, args.format);
+        }
+
+        Commands::Batch => {
+            use std::io::BufRead;
+
+            let stdin = std::io::stdin();
+            for line in stdin.lock().lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                let value = match serde_json::from_str::<serde_json::Value>(&line)
+                {
+                    Ok(value) => value,
+                    Err(e) => {
+                        let j = json!({
+                            "id": null,
+                            "error": {
+                                "code": error_code::INVALID_PARAMS,
+                                "message": e.to_string(),
+                            },
+                        });
+                        write_str(&serde_json::to_string(&j).expect(
+                            "Failed to convert batch error to json",
+                        ));
+                        continue;
+                    }
+                };
+
+                // Pull out `id` before attempting to parse the rest so a
+                // malformed/unknown `op` can still echo back which
+                // request it belongs to.
+                let id = value
+                    .get("id")
+                    .cloned()
+                    .unwrap_or(serde_json::Value::Null);
+
+                let response = match serde_json::from_value::<BatchOp>(value)
+                {
+                    Ok(op) => match run_batch_op(&server, op).await {
+                        Ok(ok) => json!({ "id": id, "ok": ok }),
+                        Err(e) => json!({ "id": id, "error": e }),
+                    },
+                    Err(e) => json!({
+                        "id": id,
+                        "error": {
+                            "code": error_code::INVALID_PARAMS,
+                            "message": e.to_string(),
+                        },
+                    }),
+                };
+
+                write_str(
+                    &serde_json::to_string(&response)
+                        .expect("Failed to convert batch response to json"),
+                );
+            }
+        }
+    }
 }